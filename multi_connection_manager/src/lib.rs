@@ -75,9 +75,19 @@
 
 use derive_more::{Deref, DerefMut, Display};
 use std::collections::HashMap;
+use std::time::Duration;
 use thiserror::Error;
 
-use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
+#[cfg(feature = "async")]
+use std::sync::Arc;
+#[cfg(feature = "async")]
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use diesel::connection::SimpleConnection;
+use diesel::Connection;
+use diesel::r2d2::{
+    ConnectionManager, CustomizeConnection, Error as R2D2ConnError, Pool, PooledConnection,
+};
 
 #[cfg(feature = "postgres")]
 use diesel::PgConnection;
@@ -88,7 +98,7 @@ use diesel::MysqlConnection;
 #[cfg(feature = "sqlite")]
 use diesel::SqliteConnection;
 
-#[derive(Debug, Display)]
+#[derive(Clone, Debug, Display)]
 pub enum DatabaseKind {
     #[cfg(feature = "postgres")]
     Postgres,
@@ -112,6 +122,160 @@ pub enum McmError {
         conn_name: String,
         error: String,
     },
+    #[error("pool with initialization statements could not be built for connection {conn_name}, database {db}: {error}")]
+    InitError {
+        db: DatabaseKind,
+        conn_name: String,
+        error: String,
+    },
+    #[cfg(feature = "async")]
+    #[error("timed out acquiring connection {conn_name} for database {db}")]
+    Timeout { db: DatabaseKind, conn_name: String },
+    #[error("url scheme requires the `{backend}` backend, which is not compiled in")]
+    BackendFeatureDisabled { backend: String },
+    #[error("could not infer a database backend from url: {url}")]
+    UnknownBackend { url: String },
+    #[error("no connection named {conn_name} is registered")]
+    UnknownConnectionName { conn_name: String },
+    #[error("duplicate connection name: {conn_name}")]
+    DuplicateConnectionName { conn_name: String },
+    #[cfg(feature = "config")]
+    #[error("failed to load configuration: {error}")]
+    ConfigError { error: String },
+}
+
+/// Runs a fixed list of statements against every physical connection r2d2 hands
+/// out. This is the hook for per-connection session state that diesel's pool
+/// would otherwise not reapply, e.g. `PRAGMA busy_timeout`/`PRAGMA foreign_keys`
+/// on SQLite or `SET statement_timeout` on Postgres/MySQL.
+#[derive(Debug)]
+struct InitCustomizer {
+    statements: Vec<String>,
+    test_transaction: bool,
+}
+
+impl<C> CustomizeConnection<C, R2D2ConnError> for InitCustomizer
+where
+    C: Connection,
+{
+    fn on_acquire(&self, conn: &mut C) -> Result<(), R2D2ConnError> {
+        for statement in &self.statements {
+            conn.batch_execute(statement)
+                .map_err(R2D2ConnError::QueryError)?;
+        }
+        // Open a test transaction that is never committed, so every write on
+        // this connection is rolled back when it is dropped. Must come after
+        // the init statements, which may need to run outside a transaction.
+        if self.test_transaction {
+            conn.begin_test_transaction()
+                .map_err(R2D2ConnError::QueryError)?;
+        }
+        Ok(())
+    }
+}
+
+/// Threads the optional r2d2 lifecycle knobs from a `ConnectionConfig` onto a
+/// pool builder. `max_size` is applied by the caller; everything else that
+/// r2d2 leaves at a default lives here so the three backend arms stay in sync.
+fn apply_pool_tuning<M>(
+    builder: diesel::r2d2::Builder<ConnectionManager<M>>,
+    config: &ConnectionConfig,
+) -> diesel::r2d2::Builder<ConnectionManager<M>>
+where
+    M: diesel::r2d2::R2D2Connection + 'static,
+{
+    let mut builder = builder.test_on_check_out(config.test_on_check_out);
+    if let Some(min_idle) = config.min_idle {
+        builder = builder.min_idle(Some(min_idle));
+    }
+    if let Some(timeout) = config.connection_timeout {
+        builder = builder.connection_timeout(timeout);
+    }
+    if let Some(timeout) = config.idle_timeout {
+        builder = builder.idle_timeout(Some(timeout));
+    }
+    if let Some(lifetime) = config.max_lifetime {
+        builder = builder.max_lifetime(Some(lifetime));
+    }
+    builder
+}
+
+/// A pooled connection acquired through the async accessors. It holds the
+/// semaphore permit that bounded the acquisition for the connection's whole
+/// lifetime, so the slot is returned to the pool's semaphore on drop. Deref to
+/// reach the underlying diesel connection.
+#[cfg(feature = "async")]
+#[derive(Deref, DerefMut)]
+pub struct AsyncConnection<M>
+where
+    M: diesel::r2d2::R2D2Connection + 'static,
+{
+    #[deref]
+    #[deref_mut]
+    conn: GenericConnection<M>,
+    _permit: OwnedSemaphorePermit,
+}
+
+/// Acquires a connection without blocking the async executor: a semaphore
+/// permit (optionally bounded by `timeout`) is taken first so no more tasks
+/// block than the pool can serve, then the blocking `pool.get()` is offloaded
+/// to `spawn_blocking`. Panics inside the blocking job are propagated by
+/// resuming the unwind on the calling task.
+#[cfg(feature = "async")]
+async fn acquire_async<M>(
+    pool: GenericPool<M>,
+    semaphore: Arc<Semaphore>,
+    db: DatabaseKind,
+    conn_name: String,
+    timeout: Option<Duration>,
+) -> McmResult<AsyncConnection<M>>
+where
+    M: diesel::r2d2::R2D2Connection + 'static,
+{
+    let permit = match timeout {
+        Some(duration) => tokio::time::timeout(duration, semaphore.acquire_owned())
+            .await
+            .map_err(|_| McmError::Timeout {
+                db: db.clone(),
+                conn_name: conn_name.clone(),
+            })?
+            .map_err(|err| McmError::R2D2Error {
+                db: db.clone(),
+                conn_name: conn_name.clone(),
+                error: err.to_string(),
+            })?,
+        None => semaphore
+            .acquire_owned()
+            .await
+            .map_err(|err| McmError::R2D2Error {
+                db: db.clone(),
+                conn_name: conn_name.clone(),
+                error: err.to_string(),
+            })?,
+    };
+
+    let conn = match tokio::task::spawn_blocking(move || pool.get()).await {
+        Ok(result) => result.map_err(|err| McmError::R2D2Error {
+            db,
+            conn_name: conn_name.clone(),
+            error: err.to_string(),
+        })?,
+        Err(join_err) => {
+            if join_err.is_panic() {
+                std::panic::resume_unwind(join_err.into_panic());
+            }
+            return Err(McmError::R2D2Error {
+                db,
+                conn_name,
+                error: join_err.to_string(),
+            });
+        }
+    };
+
+    Ok(AsyncConnection {
+        conn,
+        _permit: permit,
+    })
 }
 
 type GenericPool<M> = Pool<ConnectionManager<M>>;
@@ -146,6 +310,13 @@ pub struct ConnectionConfig {
     schema: Option<String>,
     connection_count: u32,
     options: Option<String>,
+    init_statements: Vec<String>,
+    min_idle: Option<u32>,
+    connection_timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    max_lifetime: Option<Duration>,
+    test_on_check_out: bool,
+    test_transaction: bool,
 }
 
 impl ConnectionConfig {
@@ -166,9 +337,125 @@ impl ConnectionConfig {
             schema,
             connection_count,
             options,
+            init_statements: Vec::new(),
+            min_idle: None,
+            connection_timeout: None,
+            idle_timeout: None,
+            max_lifetime: None,
+            // r2d2 validates connections on checkout by default; keep that
+            // unless the caller opts out.
+            test_on_check_out: true,
+            test_transaction: false,
         }
     }
 
+    /// Wrap every checked-out connection in a test transaction that always
+    /// rolls back, so integration tests can share a real database without
+    /// mutating it. The transaction is opened once, when r2d2 creates the
+    /// physical connection, and its writes are discarded when that physical
+    /// connection is closed. Returning a pooled connection to the pool does
+    /// not roll back, so a later checkout of the same physical connection
+    /// sees the earlier writes.
+    pub fn with_test_transaction(mut self, test_transaction: bool) -> Self {
+        self.test_transaction = test_transaction;
+        self
+    }
+
+    /// Override the rest of the r2d2 pool lifecycle left at defaults by `new`:
+    /// the floor of idle connections, how long `get` waits before failing, and
+    /// when idle/old connections are recycled.
+    pub fn with_pool_tuning(
+        mut self,
+        min_idle: Option<u32>,
+        connection_timeout: Option<Duration>,
+        idle_timeout: Option<Duration>,
+        max_lifetime: Option<Duration>,
+        test_on_check_out: bool,
+    ) -> Self {
+        self.min_idle = min_idle;
+        self.connection_timeout = connection_timeout;
+        self.idle_timeout = idle_timeout;
+        self.max_lifetime = max_lifetime;
+        self.test_on_check_out = test_on_check_out;
+        self
+    }
+
+    /// Builds a config by inferring the backend from the URL scheme instead of
+    /// passing [`DatabaseKind`] explicitly: `postgres://`/`postgresql://` is
+    /// Postgres, `mysql://` is MySQL, and a `.db` path, `file:` URI or
+    /// `:memory:` is SQLite. Returns [`McmError::BackendFeatureDisabled`] when
+    /// the scheme maps to a backend whose feature is off, and
+    /// [`McmError::UnknownBackend`] when nothing matches. Callers who want to
+    /// override detection can still use [`ConnectionConfig::new`].
+    pub fn from_url(
+        connection_name: String,
+        database_name: String,
+        database_host_url: String,
+        schema: Option<String>,
+        connection_count: u32,
+        options: Option<String>,
+    ) -> McmResult<Self> {
+        let url = database_host_url.as_str();
+        let database = if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+            #[cfg(feature = "postgres")]
+            {
+                DatabaseKind::Postgres
+            }
+            #[cfg(not(feature = "postgres"))]
+            {
+                return Err(McmError::BackendFeatureDisabled {
+                    backend: "postgres".into(),
+                });
+            }
+        } else if url.starts_with("mysql://") {
+            #[cfg(feature = "mysql")]
+            {
+                DatabaseKind::MySQL
+            }
+            #[cfg(not(feature = "mysql"))]
+            {
+                return Err(McmError::BackendFeatureDisabled {
+                    backend: "mysql".into(),
+                });
+            }
+        } else if url.ends_with(".db") || url.starts_with("file:") || url == ":memory:" {
+            #[cfg(feature = "sqlite")]
+            {
+                DatabaseKind::SQLite
+            }
+            #[cfg(not(feature = "sqlite"))]
+            {
+                return Err(McmError::BackendFeatureDisabled {
+                    backend: "sqlite".into(),
+                });
+            }
+        } else {
+            return Err(McmError::UnknownBackend {
+                url: database_host_url,
+            });
+        };
+
+        Ok(Self::new(
+            connection_name,
+            database,
+            database_name,
+            database_host_url,
+            schema,
+            connection_count,
+            options,
+        ))
+    }
+
+    /// Statements run against every checked-out connection via an r2d2
+    /// `CustomizeConnection` hook. Use this for session state that must be
+    /// reapplied on each physical connection, e.g.
+    /// `PRAGMA journal_mode = WAL` on SQLite or `SET statement_timeout` on
+    /// Postgres.
+    pub fn with_init_statements(mut self, init_statements: Vec<String>) -> Self {
+        self.init_statements = init_statements;
+        self
+    }
+
     pub fn conn_url(&self) -> String {
         match self.database {
             #[cfg(feature = "postgres")]
@@ -223,56 +510,112 @@ impl ConnectionConfig {
 }
 
 #[derive(Deref, DerefMut, Clone)]
-pub struct MultiConnectionManager(HashMap<String, MultiConnectionPool>);
+pub struct MultiConnectionManager {
+    #[deref]
+    #[deref_mut]
+    pools: HashMap<String, MultiConnectionPool>,
+    #[cfg(feature = "async")]
+    semaphores: HashMap<String, Arc<Semaphore>>,
+}
 
 impl MultiConnectionManager {
     pub fn new(value: Vec<ConnectionConfig>) -> McmResult<Self> {
-        let mut schema_manager: MultiConnectionManager = MultiConnectionManager(HashMap::new());
+        let mut pools: HashMap<String, MultiConnectionPool> = HashMap::new();
+        #[cfg(feature = "async")]
+        let mut semaphores: HashMap<String, Arc<Semaphore>> = HashMap::new();
         for config in value.into_iter() {
             let pool: MultiConnectionPool = match config.database {
                 #[cfg(feature = "postgres")]
                 DatabaseKind::Postgres => {
                     let manager = ConnectionManager::<PgConnection>::new(config.conn_url());
-                    MultiConnectionPool::Pg(
-                        Pool::builder()
-                            .max_size(config.connection_count)
-                            .build(manager)
-                            .map_err(|err| McmError::ConnectionError {
+                    let mut builder =
+                        apply_pool_tuning(Pool::builder().max_size(config.connection_count), &config);
+                    if !config.init_statements.is_empty() || config.test_transaction {
+                        builder = builder.connection_customizer(Box::new(InitCustomizer {
+                            statements: config.init_statements.clone(),
+                            test_transaction: config.test_transaction,
+                        }));
+                    }
+                    MultiConnectionPool::Pg(builder.build(manager).map_err(|err| {
+                        if config.init_statements.is_empty() {
+                            McmError::ConnectionError {
+                                db: DatabaseKind::Postgres,
+                                error: err.to_string(),
+                            }
+                        } else {
+                            McmError::InitError {
                                 db: DatabaseKind::Postgres,
+                                conn_name: config.connection_name.clone(),
                                 error: err.to_string(),
-                            })?,
-                    )
+                            }
+                        }
+                    })?)
                 }
                 #[cfg(feature = "mysql")]
                 DatabaseKind::MySQL => {
                     let manager = ConnectionManager::<MysqlConnection>::new(config.conn_url());
-                    MultiConnectionPool::Mysql(
-                        Pool::builder()
-                            .max_size(config.connection_count)
-                            .build(manager)
-                            .map_err(|err| McmError::ConnectionError {
+                    let mut builder =
+                        apply_pool_tuning(Pool::builder().max_size(config.connection_count), &config);
+                    if !config.init_statements.is_empty() || config.test_transaction {
+                        builder = builder.connection_customizer(Box::new(InitCustomizer {
+                            statements: config.init_statements.clone(),
+                            test_transaction: config.test_transaction,
+                        }));
+                    }
+                    MultiConnectionPool::Mysql(builder.build(manager).map_err(|err| {
+                        if config.init_statements.is_empty() {
+                            McmError::ConnectionError {
+                                db: DatabaseKind::MySQL,
+                                error: err.to_string(),
+                            }
+                        } else {
+                            McmError::InitError {
                                 db: DatabaseKind::MySQL,
+                                conn_name: config.connection_name.clone(),
                                 error: err.to_string(),
-                            })?,
-                    )
+                            }
+                        }
+                    })?)
                 }
                 #[cfg(feature = "sqlite")]
                 DatabaseKind::SQLite => {
                     let manager = ConnectionManager::<SqliteConnection>::new(config.conn_url());
-                    MultiConnectionPool::Sqlite(
-                        Pool::builder()
-                            .max_size(config.connection_count)
-                            .build(manager)
-                            .map_err(|err| McmError::ConnectionError {
+                    let mut builder =
+                        apply_pool_tuning(Pool::builder().max_size(config.connection_count), &config);
+                    if !config.init_statements.is_empty() || config.test_transaction {
+                        builder = builder.connection_customizer(Box::new(InitCustomizer {
+                            statements: config.init_statements.clone(),
+                            test_transaction: config.test_transaction,
+                        }));
+                    }
+                    MultiConnectionPool::Sqlite(builder.build(manager).map_err(|err| {
+                        if config.init_statements.is_empty() {
+                            McmError::ConnectionError {
+                                db: DatabaseKind::SQLite,
+                                error: err.to_string(),
+                            }
+                        } else {
+                            McmError::InitError {
                                 db: DatabaseKind::SQLite,
+                                conn_name: config.connection_name.clone(),
                                 error: err.to_string(),
-                            })?,
-                    )
+                            }
+                        }
+                    })?)
                 }
             };
-            schema_manager.insert(config.connection_name.clone(), pool);
+            #[cfg(feature = "async")]
+            semaphores.insert(
+                config.connection_name.clone(),
+                Arc::new(Semaphore::new(config.connection_count as usize)),
+            );
+            pools.insert(config.connection_name.clone(), pool);
         }
-        Ok(schema_manager)
+        Ok(MultiConnectionManager {
+            pools,
+            #[cfg(feature = "async")]
+            semaphores,
+        })
     }
 
     // hello darkness my old friend
@@ -336,6 +679,300 @@ impl MultiConnectionManager {
         };
         Ok(conn)
     }
+
+    #[cfg(all(feature = "async", feature = "postgres"))]
+    pub async fn get_pg_conn_async(
+        &self,
+        name: &'static str,
+        timeout: Option<Duration>,
+    ) -> McmResult<AsyncConnection<PgConnection>> {
+        let pool = match self.get(name).ok_or(McmError::InvalidConnectionNameError {
+            db: DatabaseKind::Postgres,
+            conn_name: name.into(),
+        })? {
+            MultiConnectionPool::Pg(pool) => pool.clone(),
+            _ => {
+                return Err(McmError::InvalidConnectionTypeError {
+                    db: DatabaseKind::Postgres,
+                })
+            }
+        };
+        let semaphore = self
+            .semaphores
+            .get(name)
+            .ok_or(McmError::InvalidConnectionNameError {
+                db: DatabaseKind::Postgres,
+                conn_name: name.into(),
+            })?
+            .clone();
+        acquire_async(pool, semaphore, DatabaseKind::Postgres, name.into(), timeout).await
+    }
+
+    #[cfg(all(feature = "async", feature = "mysql"))]
+    pub async fn get_mysql_conn_async(
+        &self,
+        name: &'static str,
+        timeout: Option<Duration>,
+    ) -> McmResult<AsyncConnection<MysqlConnection>> {
+        let pool = match self.get(name).ok_or(McmError::InvalidConnectionNameError {
+            db: DatabaseKind::MySQL,
+            conn_name: name.into(),
+        })? {
+            MultiConnectionPool::Mysql(pool) => pool.clone(),
+            _ => {
+                return Err(McmError::InvalidConnectionTypeError {
+                    db: DatabaseKind::MySQL,
+                })
+            }
+        };
+        let semaphore = self
+            .semaphores
+            .get(name)
+            .ok_or(McmError::InvalidConnectionNameError {
+                db: DatabaseKind::MySQL,
+                conn_name: name.into(),
+            })?
+            .clone();
+        acquire_async(pool, semaphore, DatabaseKind::MySQL, name.into(), timeout).await
+    }
+
+    #[cfg(all(feature = "async", feature = "sqlite"))]
+    pub async fn get_sqlite_conn_async(
+        &self,
+        name: &'static str,
+        timeout: Option<Duration>,
+    ) -> McmResult<AsyncConnection<SqliteConnection>> {
+        let pool = match self.get(name).ok_or(McmError::InvalidConnectionNameError {
+            db: DatabaseKind::SQLite,
+            conn_name: name.into(),
+        })? {
+            MultiConnectionPool::Sqlite(pool) => pool.clone(),
+            _ => {
+                return Err(McmError::InvalidConnectionTypeError {
+                    db: DatabaseKind::SQLite,
+                })
+            }
+        };
+        let semaphore = self
+            .semaphores
+            .get(name)
+            .ok_or(McmError::InvalidConnectionNameError {
+                db: DatabaseKind::SQLite,
+                conn_name: name.into(),
+            })?
+            .clone();
+        acquire_async(pool, semaphore, DatabaseKind::SQLite, name.into(), timeout).await
+    }
+}
+
+/// Generates the backend-agnostic [`DbConn`] enum and the single
+/// [`MultiConnectionManager::get_conn`] dispatcher from one list of backends,
+/// so adding or toggling a backend touches exactly one place. Mirrors the
+/// `generate_connections!` pattern in the vaultwarden db layer.
+macro_rules! generate_connections {
+    ($(($variant:ident, $conn:ty, $kind:ident, $feature:literal)),+ $(,)?) => {
+        /// A checked-out connection whose backend is only known at runtime.
+        /// Dynamic, multi-tenant routing code can hold a `DbConn` and
+        /// pattern-match instead of committing to a backend at the call site.
+        pub enum DbConn {
+            $(
+                #[cfg(feature = $feature)]
+                $variant(GenericConnection<$conn>),
+            )+
+        }
+
+        impl MultiConnectionManager {
+            /// Looks up the pool registered under `name` and hands back the
+            /// matching [`DbConn`] variant without the caller knowing the
+            /// backend in advance. The typed `get_*_conn` accessors remain for
+            /// callers who do.
+            pub fn get_conn(&self, name: &'static str) -> McmResult<DbConn> {
+                match self.get(name) {
+                    None => Err(McmError::UnknownConnectionName {
+                        conn_name: name.into(),
+                    }),
+                    $(
+                        #[cfg(feature = $feature)]
+                        Some(MultiConnectionPool::$variant(pool)) => {
+                            let conn = pool.get().map_err(|err| McmError::R2D2Error {
+                                db: DatabaseKind::$kind,
+                                conn_name: name.into(),
+                                error: err.to_string(),
+                            })?;
+                            Ok(DbConn::$variant(conn))
+                        }
+                    )+
+                }
+            }
+        }
+    };
+}
+
+generate_connections! {
+    (Pg, PgConnection, Postgres, "postgres"),
+    (Mysql, MysqlConnection, MySQL, "mysql"),
+    (Sqlite, SqliteConnection, SQLite, "sqlite"),
+}
+
+/// Declarative, serde-backed configuration. Lets operators describe the set of
+/// named connections in a `databases.toml` (or an env var holding the same
+/// TOML) and build the whole [`MultiConnectionManager`] at runtime, the way
+/// Rocket's contrib databases are configured, instead of hand-constructing a
+/// `Vec<ConnectionConfig>` in Rust.
+#[cfg(feature = "config")]
+mod config {
+    use super::{ConnectionConfig, DatabaseKind, McmError, McmResult, MultiConnectionManager};
+    use serde::Deserialize;
+    use std::time::Duration;
+
+    fn default_true() -> bool {
+        true
+    }
+
+    /// A single named connection as written in the config document. `engine` is
+    /// one of `postgres`/`postgresql`, `mysql` or `sqlite`; timeouts are given
+    /// in whole seconds.
+    #[derive(Debug, Deserialize)]
+    pub struct ConnectionDefinition {
+        pub connection_name: String,
+        pub engine: String,
+        pub database_name: String,
+        pub url: String,
+        #[serde(default)]
+        pub schema: Option<String>,
+        pub connection_count: u32,
+        #[serde(default)]
+        pub options: Option<String>,
+        #[serde(default)]
+        pub init_statements: Vec<String>,
+        #[serde(default)]
+        pub min_idle: Option<u32>,
+        #[serde(default)]
+        pub connection_timeout_secs: Option<u64>,
+        #[serde(default)]
+        pub idle_timeout_secs: Option<u64>,
+        #[serde(default)]
+        pub max_lifetime_secs: Option<u64>,
+        #[serde(default = "default_true")]
+        pub test_on_check_out: bool,
+    }
+
+    /// The top-level document: a `[[connections]]` array of table entries.
+    #[derive(Debug, Deserialize)]
+    pub struct ConnectionDefinitions {
+        pub connections: Vec<ConnectionDefinition>,
+    }
+
+    impl ConnectionDefinition {
+        fn into_config(self) -> McmResult<ConnectionConfig> {
+            let database = match self.engine.to_lowercase().as_str() {
+                "postgres" | "postgresql" => {
+                    #[cfg(feature = "postgres")]
+                    {
+                        DatabaseKind::Postgres
+                    }
+                    #[cfg(not(feature = "postgres"))]
+                    {
+                        return Err(McmError::BackendFeatureDisabled {
+                            backend: "postgres".into(),
+                        });
+                    }
+                }
+                "mysql" => {
+                    #[cfg(feature = "mysql")]
+                    {
+                        DatabaseKind::MySQL
+                    }
+                    #[cfg(not(feature = "mysql"))]
+                    {
+                        return Err(McmError::BackendFeatureDisabled {
+                            backend: "mysql".into(),
+                        });
+                    }
+                }
+                "sqlite" => {
+                    #[cfg(feature = "sqlite")]
+                    {
+                        DatabaseKind::SQLite
+                    }
+                    #[cfg(not(feature = "sqlite"))]
+                    {
+                        return Err(McmError::BackendFeatureDisabled {
+                            backend: "sqlite".into(),
+                        });
+                    }
+                }
+                other => {
+                    return Err(McmError::ConfigError {
+                        error: format!("unknown database engine: {other}"),
+                    });
+                }
+            };
+
+            Ok(ConnectionConfig::new(
+                self.connection_name,
+                database,
+                self.database_name,
+                self.url,
+                self.schema,
+                self.connection_count,
+                self.options,
+            )
+            .with_init_statements(self.init_statements)
+            .with_pool_tuning(
+                self.min_idle,
+                self.connection_timeout_secs.map(Duration::from_secs),
+                self.idle_timeout_secs.map(Duration::from_secs),
+                self.max_lifetime_secs.map(Duration::from_secs),
+                self.test_on_check_out,
+            ))
+        }
+    }
+
+    impl MultiConnectionManager {
+        /// Builds a manager from a TOML document, rejecting duplicate
+        /// `connection_name`s with [`McmError::DuplicateConnectionName`] and
+        /// backends whose feature is not compiled in.
+        pub fn from_toml_str(toml_str: &str) -> McmResult<Self> {
+            let document: ConnectionDefinitions = toml::from_str(toml_str)
+                .map_err(|err| McmError::ConfigError {
+                    error: err.to_string(),
+                })?;
+            Self::from_definitions(document.connections)
+        }
+
+        /// Builds a manager from a TOML file on disk.
+        pub fn from_config_file(path: impl AsRef<std::path::Path>) -> McmResult<Self> {
+            let contents =
+                std::fs::read_to_string(path).map_err(|err| McmError::ConfigError {
+                    error: err.to_string(),
+                })?;
+            Self::from_toml_str(&contents)
+        }
+
+        /// Builds a manager from the TOML document held in the named
+        /// environment variable.
+        pub fn from_env(var: &str) -> McmResult<Self> {
+            let contents = std::env::var(var).map_err(|err| McmError::ConfigError {
+                error: err.to_string(),
+            })?;
+            Self::from_toml_str(&contents)
+        }
+
+        fn from_definitions(definitions: Vec<ConnectionDefinition>) -> McmResult<Self> {
+            let mut seen = std::collections::HashSet::with_capacity(definitions.len());
+            let mut configs = Vec::with_capacity(definitions.len());
+            for definition in definitions {
+                if !seen.insert(definition.connection_name.clone()) {
+                    return Err(McmError::DuplicateConnectionName {
+                        conn_name: definition.connection_name,
+                    });
+                }
+                configs.push(definition.into_config()?);
+            }
+            Self::new(configs)
+        }
+    }
 }
 
 /*
@@ -1,9 +1,14 @@
 extern crate derive_more;
 use derive_more::{Deref, DerefMut, Display};
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
 use diesel::{
-    r2d2::{ConnectionManager, Pool, PooledConnection},
+    connection::SimpleConnection,
+    r2d2::{ConnectionManager, CustomizeConnection, Error as R2D2ConnError, Pool, PooledConnection},
     MysqlConnection, PgConnection, SqliteConnection,
 };
 
@@ -14,9 +19,32 @@ pub enum DatabaseKind {
     SQLite,
 }
 
+/// Runs a fixed list of statements against every physical connection r2d2 hands
+/// out, via diesel's `CustomizeConnection` hook. This is where per-session state
+/// that the pool would otherwise not reapply lives, e.g. `PRAGMA foreign_keys`
+/// on SQLite or `SET statement_timeout` on Postgres/MySQL.
+#[derive(Debug)]
+struct InitCustomizer {
+    statements: Vec<String>,
+}
+
+impl<C> CustomizeConnection<C, R2D2ConnError> for InitCustomizer
+where
+    C: SimpleConnection,
+{
+    fn on_acquire(&self, conn: &mut C) -> Result<(), R2D2ConnError> {
+        for statement in &self.statements {
+            conn.batch_execute(statement)
+                .map_err(R2D2ConnError::QueryError)?;
+        }
+        Ok(())
+    }
+}
+
 type GenericPool<M> = Pool<ConnectionManager<M>>;
 type GenericConnection<M> = PooledConnection<ConnectionManager<M>>;
 type ResultConnection<M> = anyhow::Result<GenericConnection<M>>;
+type ResultConnectionGuard<M> = anyhow::Result<AsyncConnection<M>>;
 
 #[derive(Clone, Debug)]
 pub enum MultiConnectionPool {
@@ -42,6 +70,12 @@ pub struct ConnectionConfig {
     schema: String,
     connection_count: u32,
     options: Option<String>,
+    init_statements: Vec<String>,
+    min_idle: Option<u32>,
+    connection_timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    max_lifetime: Option<Duration>,
+    test_on_check_out: bool,
 }
 
 impl ConnectionConfig {
@@ -62,9 +96,45 @@ impl ConnectionConfig {
             schema,
             connection_count,
             options,
+            init_statements: Vec::new(),
+            min_idle: None,
+            connection_timeout: None,
+            idle_timeout: None,
+            max_lifetime: None,
+            // r2d2 validates connections on checkout by default; keep that
+            // unless the caller opts out.
+            test_on_check_out: true,
         }
     }
 
+    /// Statements run against every checked-out connection via an r2d2
+    /// `CustomizeConnection` hook, for session state that must be reapplied on
+    /// each physical connection.
+    pub fn with_init_statements(mut self, init_statements: Vec<String>) -> Self {
+        self.init_statements = init_statements;
+        self
+    }
+
+    /// Override the rest of the r2d2 pool lifecycle left at defaults by `new`:
+    /// the floor of idle connections, how long `get` waits before failing, and
+    /// when idle/old connections are recycled. A bounded `connection_timeout`
+    /// in particular lets `get` fail fast instead of hanging under load.
+    pub fn with_pool_tuning(
+        mut self,
+        min_idle: Option<u32>,
+        connection_timeout: Option<Duration>,
+        idle_timeout: Option<Duration>,
+        max_lifetime: Option<Duration>,
+        test_on_check_out: bool,
+    ) -> Self {
+        self.min_idle = min_idle;
+        self.connection_timeout = connection_timeout;
+        self.idle_timeout = idle_timeout;
+        self.max_lifetime = max_lifetime;
+        self.test_on_check_out = test_on_check_out;
+        self
+    }
+
     pub fn conn_url(&self) -> String {
         match self.database {
             DatabaseKind::Postgres => self.pg_conn_url(),
@@ -109,56 +179,155 @@ impl ConnectionConfig {
 }
 
 #[derive(Deref, DerefMut, Clone)]
-pub struct PgSchemaManager(HashMap<String, MultiConnectionPool>);
+pub struct PgSchemaManager {
+    #[deref]
+    #[deref_mut]
+    pools: HashMap<String, MultiConnectionPool>,
+    semaphores: HashMap<String, Arc<Semaphore>>,
+}
+
+/// Applies the common r2d2 builder settings (pool size, the pool tuning knobs
+/// and the init-statement customizer) for a backend, so the three arms of
+/// [`build_pool`] stay in sync.
+fn pool_builder<M>(config: &ConnectionConfig) -> diesel::r2d2::Builder<ConnectionManager<M>>
+where
+    M: diesel::r2d2::R2D2Connection + 'static,
+{
+    let mut builder = Pool::builder()
+        .max_size(config.connection_count)
+        .test_on_check_out(config.test_on_check_out);
+    if let Some(min_idle) = config.min_idle {
+        builder = builder.min_idle(Some(min_idle));
+    }
+    if let Some(timeout) = config.connection_timeout {
+        builder = builder.connection_timeout(timeout);
+    }
+    if let Some(timeout) = config.idle_timeout {
+        builder = builder.idle_timeout(Some(timeout));
+    }
+    if let Some(lifetime) = config.max_lifetime {
+        builder = builder.max_lifetime(Some(lifetime));
+    }
+    if !config.init_statements.is_empty() {
+        builder = builder.connection_customizer(Box::new(InitCustomizer {
+            statements: config.init_statements.clone(),
+        }));
+    }
+    builder
+}
+
+/// Builds a single pool, surfacing which `connection_name` failed and why
+/// instead of panicking.
+fn build_pool(config: &ConnectionConfig) -> anyhow::Result<MultiConnectionPool> {
+    use anyhow::Context;
+    let pool = match config.database {
+        DatabaseKind::Postgres => {
+            let manager = ConnectionManager::<PgConnection>::new(config.conn_url());
+            MultiConnectionPool::Pg(pool_builder::<PgConnection>(config).build(manager).with_context(
+                || format!("Invalid config provided, {}", config.connection_name),
+            )?)
+        }
+        DatabaseKind::MySQL => {
+            let manager = ConnectionManager::<MysqlConnection>::new(config.conn_url());
+            MultiConnectionPool::Mysql(pool_builder::<MysqlConnection>(config).build(manager).with_context(
+                || format!("Invalid config provided, {}", config.connection_name),
+            )?)
+        }
+        DatabaseKind::SQLite => {
+            let manager = ConnectionManager::<SqliteConnection>::new(config.conn_url());
+            MultiConnectionPool::Sqlite(pool_builder::<SqliteConnection>(config).build(manager).with_context(
+                || format!("Invalid config provided, {}", config.connection_name),
+            )?)
+        }
+    };
+    Ok(pool)
+}
 
 impl<const N: usize> From<[ConnectionConfig; N]> for PgSchemaManager {
     fn from(value: [ConnectionConfig; N]) -> Self {
-        let mut schema_manager: PgSchemaManager = PgSchemaManager(HashMap::new());
-        for config in value.into_iter() {
-            let pool: MultiConnectionPool = match config.database {
-                DatabaseKind::Postgres => {
-                    let manager = ConnectionManager::<PgConnection>::new(config.conn_url());
-                    MultiConnectionPool::Pg(
-                        Pool::builder()
-                            .max_size(config.connection_count)
-                            .build(manager)
-                            .expect(
-                                format!("Invalid config provided, {}", config.connection_name)
-                                    .as_str(),
-                            ),
-                    )
-                }
-                DatabaseKind::MySQL => {
-                    let manager = ConnectionManager::<MysqlConnection>::new(config.conn_url());
-                    MultiConnectionPool::Mysql(
-                        Pool::builder()
-                            .max_size(config.connection_count)
-                            .build(manager)
-                            .expect(
-                                format!("Invalid config provided, {}", config.connection_name)
-                                    .as_str(),
-                            ),
-                    )
-                }
-                DatabaseKind::SQLite => {
-                    let manager = ConnectionManager::<SqliteConnection>::new(config.conn_url());
-                    MultiConnectionPool::Sqlite(
-                        Pool::builder()
-                            .max_size(config.connection_count)
-                            .build(manager)
-                            .expect(
-                                format!("Invalid config provided, {}", config.connection_name)
-                                    .as_str(),
-                            ),
-                    )
-                }
-            };
-            schema_manager.insert(config.connection_name.clone(), pool);
+        Self::try_from(value).expect("Invalid config provided")
+    }
+}
+
+impl<const N: usize> TryFrom<[ConnectionConfig; N]> for PgSchemaManager {
+    type Error = anyhow::Error;
+
+    fn try_from(value: [ConnectionConfig; N]) -> anyhow::Result<Self> {
+        Self::try_from_configs(value)
+    }
+}
+
+impl PgSchemaManager {
+    /// Builds a manager from any iterator of configs, returning an error that
+    /// names the failing connection rather than aborting the process.
+    pub fn try_from_configs(
+        configs: impl IntoIterator<Item = ConnectionConfig>,
+    ) -> anyhow::Result<Self> {
+        let mut pools: HashMap<String, MultiConnectionPool> = HashMap::new();
+        let mut semaphores: HashMap<String, Arc<Semaphore>> = HashMap::new();
+        for config in configs {
+            let pool = build_pool(&config)?;
+            semaphores.insert(
+                config.connection_name.clone(),
+                Arc::new(Semaphore::new(config.connection_count as usize)),
+            );
+            pools.insert(config.connection_name.clone(), pool);
         }
-        schema_manager
+        Ok(PgSchemaManager { pools, semaphores })
     }
 }
 
+/// A pooled connection acquired through the async accessors. It holds the
+/// semaphore permit for the connection's whole lifetime, so the slot is
+/// returned to the pool's semaphore on drop. Deref to reach the underlying
+/// diesel connection.
+#[derive(Deref, DerefMut)]
+pub struct AsyncConnection<M>
+where
+    M: diesel::r2d2::R2D2Connection + 'static,
+{
+    #[deref]
+    #[deref_mut]
+    conn: GenericConnection<M>,
+    _permit: OwnedSemaphorePermit,
+}
+
+/// Acquires a connection without blocking the async executor: a semaphore
+/// permit (optionally bounded by `timeout`) is taken first so no more tasks
+/// block than the pool can serve, then the blocking `pool.get()` is offloaded
+/// to `spawn_blocking`. Panics inside the blocking job are propagated by
+/// resuming the unwind on the calling task.
+async fn acquire_async<M>(
+    pool: GenericPool<M>,
+    semaphore: Arc<Semaphore>,
+    timeout: Option<Duration>,
+) -> ResultConnectionGuard<M>
+where
+    M: diesel::r2d2::R2D2Connection + 'static,
+{
+    let permit = match timeout {
+        Some(duration) => tokio::time::timeout(duration, semaphore.acquire_owned())
+            .await
+            .map_err(|_| anyhow::anyhow!("timed out acquiring connection"))??,
+        None => semaphore.acquire_owned().await?,
+    };
+
+    let conn = match tokio::task::spawn_blocking(move || pool.get()).await {
+        Ok(result) => result?,
+        Err(join_err) => {
+            if join_err.is_panic() {
+                std::panic::resume_unwind(join_err.into_panic());
+            }
+            anyhow::bail!("connection acquisition task failed: {join_err}");
+        }
+    };
+
+    Ok(AsyncConnection {
+        conn,
+        _permit: permit,
+    })
+}
+
 impl PgSchemaManager {
     // hello darkness my old friend
     // This would have been easier in haskell
@@ -195,4 +364,243 @@ impl PgSchemaManager {
         };
         Ok(conn)
     }
+
+    pub fn try_get_pg_conn(&self, name: &str) -> ResultConnection<PgConnection> {
+        match self
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Invalid connection name provided: {name}"))?
+        {
+            MultiConnectionPool::Pg(conn) => Ok(conn.get()?),
+            _ => anyhow::bail!("Connection is not of type Postgres"),
+        }
+    }
+
+    pub fn try_get_mysql_conn(&self, name: &str) -> ResultConnection<MysqlConnection> {
+        match self
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Invalid connection name provided: {name}"))?
+        {
+            MultiConnectionPool::Mysql(conn) => Ok(conn.get()?),
+            _ => anyhow::bail!("Connection is not of type Mysql"),
+        }
+    }
+
+    pub fn try_get_sqlite_conn(&self, name: &str) -> ResultConnection<SqliteConnection> {
+        match self
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Invalid connection name provided: {name}"))?
+        {
+            MultiConnectionPool::Sqlite(conn) => Ok(conn.get()?),
+            _ => anyhow::bail!("Connection is not of type Sqlite"),
+        }
+    }
+
+    fn semaphore(&self, name: &str) -> anyhow::Result<Arc<Semaphore>> {
+        self.semaphores
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Invalid connection name provided: {name}"))
+    }
+
+    pub async fn get_pg_conn_async(
+        &self,
+        name: String,
+        timeout: Option<Duration>,
+    ) -> ResultConnectionGuard<PgConnection> {
+        let pool = match self.get(&name) {
+            Some(MultiConnectionPool::Pg(pool)) => pool.clone(),
+            Some(_) => anyhow::bail!("Connection is not of type Postgres"),
+            None => anyhow::bail!("Invalid connection name provided: {name}"),
+        };
+        acquire_async(pool, self.semaphore(&name)?, timeout).await
+    }
+
+    pub async fn get_mysql_conn_async(
+        &self,
+        name: String,
+        timeout: Option<Duration>,
+    ) -> ResultConnectionGuard<MysqlConnection> {
+        let pool = match self.get(&name) {
+            Some(MultiConnectionPool::Mysql(pool)) => pool.clone(),
+            Some(_) => anyhow::bail!("Connection is not of type Mysql"),
+            None => anyhow::bail!("Invalid connection name provided: {name}"),
+        };
+        acquire_async(pool, self.semaphore(&name)?, timeout).await
+    }
+
+    pub async fn get_sqlite_conn_async(
+        &self,
+        name: String,
+        timeout: Option<Duration>,
+    ) -> ResultConnectionGuard<SqliteConnection> {
+        let pool = match self.get(&name) {
+            Some(MultiConnectionPool::Sqlite(pool)) => pool.clone(),
+            Some(_) => anyhow::bail!("Connection is not of type Sqlite"),
+            None => anyhow::bail!("Invalid connection name provided: {name}"),
+        };
+        acquire_async(pool, self.semaphore(&name)?, timeout).await
+    }
+
+    /// Type-directed accessor: the connection type picks the pool variant, so
+    /// callers get one uniform API instead of three near-identical `get_*`
+    /// methods. Extend support for a new diesel connection type by implementing
+    /// [`Poolable`] for it.
+    pub fn get_conn<C: Poolable>(&self, name: &str) -> ResultConnection<C> {
+        let pool = self
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Invalid connection name provided: {name}"))?;
+        let pool = C::from_pool(pool)
+            .ok_or_else(|| anyhow::anyhow!("Connection is not of type {}", C::ENGINE))?;
+        Ok(pool.get()?)
+    }
+}
+
+/// Associates a diesel connection type with its [`MultiConnectionPool`]
+/// variant, analogous to Rocket's `Poolable`. Implementing this for a new
+/// diesel-compatible connection type lets [`PgSchemaManager::get_conn`] return
+/// it without editing the enum-matching accessors.
+pub trait Poolable: diesel::r2d2::R2D2Connection + 'static {
+    /// Human-readable engine name, used in type-mismatch errors.
+    const ENGINE: &'static str;
+
+    /// Borrows the matching pool out of a [`MultiConnectionPool`], or `None`
+    /// when the variant is for a different backend.
+    fn from_pool(pool: &MultiConnectionPool) -> Option<&GenericPool<Self>>;
+}
+
+impl Poolable for PgConnection {
+    const ENGINE: &'static str = "Postgres";
+
+    fn from_pool(pool: &MultiConnectionPool) -> Option<&GenericPool<Self>> {
+        match pool {
+            MultiConnectionPool::Pg(pool) => Some(pool),
+            _ => None,
+        }
+    }
+}
+
+impl Poolable for MysqlConnection {
+    const ENGINE: &'static str = "Mysql";
+
+    fn from_pool(pool: &MultiConnectionPool) -> Option<&GenericPool<Self>> {
+        match pool {
+            MultiConnectionPool::Mysql(pool) => Some(pool),
+            _ => None,
+        }
+    }
+}
+
+impl Poolable for SqliteConnection {
+    const ENGINE: &'static str = "Sqlite";
+
+    fn from_pool(pool: &MultiConnectionPool) -> Option<&GenericPool<Self>> {
+        match pool {
+            MultiConnectionPool::Sqlite(pool) => Some(pool),
+            _ => None,
+        }
+    }
+}
+
+/// Declarative, serde-backed configuration in the style of Rocket's contrib
+/// databases: a `[databases.<name>]` table per connection carrying the engine,
+/// url and pool sub-keys. This decouples connection topology from the binary so
+/// operators can retune tenants without recompiling.
+mod config {
+    use super::{ConnectionConfig, DatabaseKind, PgSchemaManager};
+    use serde::Deserialize;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    fn default_true() -> bool {
+        true
+    }
+
+    /// A single named connection as written in the config document. `engine` is
+    /// one of `postgres`/`postgresql`, `mysql` or `sqlite`; timeouts are given
+    /// in whole seconds.
+    #[derive(Debug, Deserialize)]
+    pub struct ConnectionDefinition {
+        pub engine: String,
+        pub url: String,
+        pub database_name: String,
+        #[serde(default)]
+        pub schema: String,
+        #[serde(alias = "pool_size")]
+        pub connection_count: u32,
+        #[serde(default)]
+        pub options: Option<String>,
+        #[serde(default)]
+        pub init_statements: Vec<String>,
+        #[serde(default)]
+        pub min_idle: Option<u32>,
+        #[serde(default)]
+        pub connection_timeout_secs: Option<u64>,
+        #[serde(default)]
+        pub idle_timeout_secs: Option<u64>,
+        #[serde(default)]
+        pub max_lifetime_secs: Option<u64>,
+        #[serde(default = "default_true")]
+        pub test_on_check_out: bool,
+    }
+
+    /// The top-level document: `[databases.<name>]` tables keyed by connection
+    /// name, so names are unique by construction.
+    #[derive(Debug, Deserialize)]
+    pub struct DatabasesConfig {
+        pub databases: HashMap<String, ConnectionDefinition>,
+    }
+
+    impl ConnectionDefinition {
+        fn into_config(self, connection_name: String) -> anyhow::Result<ConnectionConfig> {
+            let database = match self.engine.to_lowercase().as_str() {
+                "postgres" | "postgresql" => DatabaseKind::Postgres,
+                "mysql" => DatabaseKind::MySQL,
+                "sqlite" => DatabaseKind::SQLite,
+                other => anyhow::bail!("unknown database engine: {other}"),
+            };
+            Ok(ConnectionConfig::new(
+                connection_name,
+                database,
+                self.database_name,
+                self.url,
+                self.schema,
+                self.connection_count,
+                self.options,
+            )
+            .with_init_statements(self.init_statements)
+            .with_pool_tuning(
+                self.min_idle,
+                self.connection_timeout_secs.map(Duration::from_secs),
+                self.idle_timeout_secs.map(Duration::from_secs),
+                self.max_lifetime_secs.map(Duration::from_secs),
+                self.test_on_check_out,
+            ))
+        }
+    }
+
+    impl PgSchemaManager {
+        /// Builds a manager from a TOML document.
+        pub fn from_toml_str(toml_str: &str) -> anyhow::Result<Self> {
+            let document: DatabasesConfig = toml::from_str(toml_str)?;
+            let configs = document
+                .databases
+                .into_iter()
+                .map(|(name, definition)| definition.into_config(name))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            Self::try_from_configs(configs)
+        }
+
+        /// Builds a manager from a `databases.toml`-style file on disk.
+        pub fn from_config_file(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+            let contents = std::fs::read_to_string(path)?;
+            Self::from_toml_str(&contents)
+        }
+
+        /// Builds a manager from the TOML document held in the named
+        /// environment variable.
+        pub fn from_env(prefix: &str) -> anyhow::Result<Self> {
+            let contents = std::env::var(prefix)?;
+            Self::from_toml_str(&contents)
+        }
+    }
 }